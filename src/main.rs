@@ -1,18 +1,55 @@
 //! A simplified implementation of the classic game "Breakout".
+use std::collections::HashMap;
 use std::f32::consts::TAU;
+use std::time::Duration;
 
-use bevy::math::bounding::Aabb2d;
-use bevy::math::bounding::IntersectsVolume;
+use bevy::asset::LoadState;
 use bevy::prelude::*;
+use bevy_ggrs::{
+    ggrs, AddRollbackCommandExtension, GgrsApp, GgrsPlugin, GgrsSchedule, LocalInputs,
+    LocalPlayers, PlayerInputs, ReadInputs,
+};
+use bevy_hanabi::prelude::*;
+use bevy_rapier2d::plugin::PhysicsSet;
+use bevy_rapier2d::prelude::{
+    ActiveEvents, Ccd, Collider, CollisionEvent as RapierCollisionEvent, NoUserData,
+    RapierConfiguration, RapierPhysicsPlugin, Restitution, RigidBody, TimestepMode, Velocity,
+};
 use bevy_turborand::prelude::*;
+use bytemuck::{Pod, Zeroable};
+use ggrs::{PlayerType, SessionBuilder, UdpNonBlockingSocket};
 
 const INIT_DIFFICULTY: f64 = 0.05;
 const STARTING_HEALTH: usize = 5;
 const DIFFICULTY_INCREMENT: f64 = 0.001;
 
+// The difficulty value at which the spawn interval bottoms out at SPAWN_INTERVAL_FLOOR.
+const MAX_DIFFICULTY: f64 = 1.0;
+const SPAWN_INTERVAL_START: f32 = 1.0;
+const SPAWN_INTERVAL_FLOOR: f32 = 0.08;
+
+// Rollback netcode runs in lockstep at a fixed rate, so simulation systems use this
+// constant instead of `Res<Time>` wall-clock deltas to stay deterministic across peers.
+const ROLLBACK_FPS: usize = 60;
+const ROLLBACK_DT: f32 = 1.0 / ROLLBACK_FPS as f32;
+
+const INPUT_LEFT: u8 = 1 << 0;
+const INPUT_RIGHT: u8 = 1 << 1;
+
+// Shared ahead of time so both peers seed their meteor RNG identically; a real matchmaker
+// would exchange this during session setup instead of hard-coding it.
+const NET_RNG_SEED: u64 = 0xC0FFEE_u64;
+
 const PLAYER_SPEED: f32 = 500.0;
 const METEOR_SPEED: f32 = 250.0;
 
+const PLAYER_WIDTH: f32 = 150.;
+const PLAYER_HEIGHT: f32 = 250.;
+const PLAYER_SCALE: f32 = 0.2;
+
+const WALL_THICKNESS: f32 = 10.0;
+const WALL_COLOR: Color = Color::rgb(0.4, 0.4, 0.4);
+
 // Small meteor dimensions
 const SMALL_METEOR_WIDTH: f32 = 108.;
 const SMALL_METEOR_HEIGHT: f32 = 92.;
@@ -38,10 +75,38 @@ const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
 
 #[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
 enum GameState {
+    Loading,
     InGame,
     Dead,
 }
 
+/// `ggrs::Config` for the two-player co-op rollback session.
+#[derive(Debug)]
+struct NetConfig;
+
+impl ggrs::Config for NetConfig {
+    type Input = BoxInput;
+    type State = u8;
+    type Address = std::net::SocketAddr;
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+struct BoxInput {
+    inp: u8,
+}
+
+/// Present once a P2P session has been started; marks the game as running in netplay co-op.
+#[derive(Resource)]
+struct NetplaySession {
+    local_handle: usize,
+}
+
+/// Meteor RNG used by the rollback spawn systems, seeded identically on both peers so both
+/// clients spawn the same meteors. Kept off the `Player` entity since co-op has two of them.
+#[derive(Resource, Clone)]
+struct MeteorRng(RngComponent);
+
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 struct GamePlaySet;
 
@@ -57,21 +122,60 @@ fn main() {
                 ..default()
             }
         ))
-        .insert_state(GameState::InGame)
+        .add_plugins(
+            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0).in_schedule(GgrsSchedule),
+        )
+        .add_plugins(HanabiPlugin)
+        .add_plugins(GgrsPlugin::<NetConfig>::default())
+        .insert_resource(RapierConfiguration {
+            gravity: Vec2::ZERO,
+            // Physics now steps inside `GgrsSchedule` at the rollback rate, so it must use a
+            // fixed dt instead of `Res<Time>` to stay deterministic and resimulate identically.
+            timestep_mode: TimestepMode::Fixed {
+                dt: ROLLBACK_DT,
+                substeps: 1,
+            },
+            ..RapierConfiguration::new(100.0)
+        })
+        .insert_state(GameState::Loading)
         .insert_resource(ClearColor(BACKGROUND_COLOR))
-        .init_resource::<GlobalRng>()
         .add_event::<CollisionEvent>()
         .add_event::<DeathEvent>()
+        .set_rollback_schedule_fps(ROLLBACK_FPS)
+        .rollback_component_with_clone::<Transform>()
+        .rollback_component_with_clone::<Velocity>()
+        .rollback_component_with_clone::<Health>()
+        .rollback_resource_with_clone::<Difficulty>()
+        .rollback_resource_with_clone::<SpawnTimer>()
+        .rollback_resource_with_clone::<MeteorRng>()
+        .add_systems(ReadInputs, read_local_inputs)
+        .add_systems(
+            GgrsSchedule,
+            (
+                increase_difficulty_rollback,
+                update_spawn_timer_rollback,
+                maybe_spawn_meteor_rollback,
+                move_player_rollback,
+            )
+                .chain()
+                .before(PhysicsSet::SyncBackend)
+                .run_if(in_state(GameState::InGame)),
+        )
+        .add_systems(
+            GgrsSchedule,
+            (read_collision_events, apply_damage)
+                .chain()
+                .after(PhysicsSet::Writeback)
+                .run_if(in_state(GameState::InGame)),
+        )
         .add_systems(
             Update,
             (
                 despawn_offscreen,
-                // Might need chaining?
-                move_player,
-                apply_velocity,
-                check_for_collisions,
-                apply_damage,
-                apply_rotations,
+                play_collision_sound,
+                play_death_sound,
+                spawn_meteor_particles,
+                despawn_finished_particles,
                 update_health_bar,
                 animate_sprites,
                 update_scoreboard,
@@ -79,10 +183,10 @@ fn main() {
             )
                 .in_set(GamePlaySet),
         )
-        .add_systems(Startup, init)
+        .add_systems(Startup, (start_p2p_session, load_assets, init).chain())
         .add_systems(
-            FixedUpdate,
-            (maybe_spawn_meteor, increase_difficulty).in_set(GamePlaySet),
+            Update,
+            check_assets_ready.run_if(in_state(GameState::Loading)),
         )
         .add_systems(Update, (retry_button_system).in_set(DeadScreenSet))
         .add_systems(
@@ -92,46 +196,49 @@ fn main() {
                 bevy::window::close_on_esc,
             ),
         )
-        .add_systems(OnEnter(GameState::InGame), setup)
+        .add_systems(OnEnter(GameState::Loading), on_loading_enter)
+        .add_systems(OnExit(GameState::Loading), on_loading_exit)
+        .add_systems(OnEnter(GameState::InGame), (setup, start_background_music))
         .add_systems(OnEnter(GameState::Dead), on_death_enter)
         .add_systems(OnExit(GameState::Dead), on_death_exit)
         .configure_sets(Update, (GamePlaySet.run_if(in_state(GameState::InGame)),))
-        .configure_sets(
-            FixedUpdate,
-            (GamePlaySet.run_if(in_state(GameState::InGame)),),
-        )
         .configure_sets(Update, (DeadScreenSet.run_if(in_state(GameState::Dead)),))
         .run();
 }
 
-#[derive(Component)]
-struct Player;
+/// The GGRS player handle controlling this paddle (0 in single-player).
+#[derive(Component, Clone, Copy)]
+struct Player(usize);
 
 #[derive(Component)]
 #[component(storage = "SparseSet")]
 struct Meteor;
 
-#[derive(Component, Deref, DerefMut)]
-#[component(storage = "SparseSet")]
-struct Velocity(Vec2);
-
 #[derive(Component)]
-#[component(storage = "SparseSet")]
-struct Collider(Vec2);
+struct Wall;
 
-#[derive(Component, Deref, DerefMut)]
+#[derive(Component, Clone, Deref, DerefMut)]
 struct Health(usize);
 
-#[derive(Event, Default)]
-struct CollisionEvent;
+#[derive(Event)]
+struct CollisionEvent {
+    /// Where the meteor was destroyed, so VFX can be spawned in the right place.
+    position: Vec3,
+    /// Which paddle it hit, so co-op damage lands on the right player.
+    player: Entity,
+}
 
 #[derive(Event, Default)]
 struct DeathEvent;
 
-/// Percentage difficulty, represents chance of meteor spawning in given FixedUpdate tick
-#[derive(Resource)]
+/// Difficulty level, ramped up over time; [`SpawnTimer`]'s interval shrinks as this rises.
+#[derive(Resource, Clone)]
 struct Difficulty(f64, Timer);
 
+/// Repeating timer that fires a meteor spawn; its interval shrinks as [`Difficulty`] rises.
+#[derive(Resource, Clone)]
+struct SpawnTimer(Timer);
+
 struct MeteorType {
     texture: Handle<Image>,
     dimensions: Vec2,
@@ -143,9 +250,6 @@ struct MeteorRes {
     types: Vec<MeteorType>,
 }
 
-#[derive(Component)]
-struct RotationalMomentum(f32);
-
 #[derive(Component)]
 struct AnimationTimer(Timer, usize, usize);
 
@@ -156,12 +260,40 @@ struct Scoreboard {
     tick_timer: Timer,
 }
 
+#[derive(Resource)]
+struct Sounds {
+    collision: Handle<AudioSource>,
+    death: Handle<AudioSource>,
+    background: Handle<AudioSource>,
+}
+
+#[derive(Resource)]
+struct ParticleEffects {
+    meteor_burst: Handle<EffectAsset>,
+}
+
+/// Marks a one-shot particle burst entity for cleanup once its effect has played out.
+#[derive(Component)]
+struct ParticleBurst(Timer);
+
 #[derive(Component)]
 struct ScoreboardUi;
 
 #[derive(Component)]
 struct HealthBarUi(usize);
 
+#[derive(Component)]
+struct LoadingUi;
+
+/// Images and atlas layouts loaded once at `Startup`. The images are polled for readiness
+/// before leaving `GameState::Loading` so `setup` never builds sprites against an untextured
+/// quad; the layouts are built in memory and are not polled.
+#[derive(Resource, Default)]
+struct AssetLoader {
+    images: HashMap<&'static str, Handle<Image>>,
+    layouts: HashMap<&'static str, Handle<TextureAtlasLayout>>,
+}
+
 fn bottom(w: &Window) -> f32 {
     return w.height() / -2.;
 }
@@ -174,6 +306,10 @@ fn right(w: &Window) -> f32 {
     return w.width() / 2.;
 }
 
+fn top(w: &Window) -> f32 {
+    return w.height() / 2.;
+}
+
 fn animate_sprites(
     time: Res<Time>,
     mut query: Query<(&mut AnimationTimer, &mut TextureAtlas)>,
@@ -190,7 +326,136 @@ fn animate_sprites(
     }
 }
 
-fn init(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn load_assets(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+) {
+    let mut images = HashMap::new();
+    images.insert("meteor_big", asset_server.load("spr_meteor_big.png"));
+    images.insert("meteor_small", asset_server.load("spr_meteor_small.png"));
+    images.insert("player", asset_server.load("PlayerSheetNormal.png"));
+    images.insert("heart", asset_server.load("Heart.png"));
+
+    let mut layouts = HashMap::new();
+    layouts.insert(
+        "player",
+        texture_atlas_layouts.add(TextureAtlasLayout::from_grid(
+            Vec2::new(PLAYER_WIDTH, PLAYER_HEIGHT),
+            5,
+            1,
+            None,
+            None,
+        )),
+    );
+
+    commands.insert_resource(AssetLoader { images, layouts });
+}
+
+fn check_assets_ready(
+    asset_server: Res<AssetServer>,
+    asset_loader: Res<AssetLoader>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let all_loaded = asset_loader
+        .images
+        .values()
+        .all(|handle| asset_server.get_load_state(handle) == Some(LoadState::Loaded));
+
+    if all_loaded {
+        next_state.set(GameState::InGame);
+    }
+}
+
+fn on_loading_enter(mut commands: Commands) {
+    commands.spawn((
+        LoadingUi,
+        TextBundle::from_section(
+            "Loading...",
+            TextStyle {
+                font_size: SCOREBOARD_FONT_SIZE,
+                color: TEXT_COLOR,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(45.),
+            left: Val::Percent(42.),
+            ..default()
+        }),
+    ));
+}
+
+fn on_loading_exit(mut commands: Commands, loading_ui: Query<Entity, With<LoadingUi>>) {
+    for e in &loading_ui {
+        commands.entity(e).despawn();
+    }
+}
+
+/// Starts this run's GGRS session. Physics and the rest of the simulation only ever step
+/// inside `GgrsSchedule`, so every run needs one, not just co-op: single-player gets a local
+/// `SyncTestSession` (1 player, no network); co-op gets a real `P2PSession`.
+///
+/// Two-player co-op is opted into with `<local-port> <remote-addr> <local-handle>`, e.g. on
+/// one machine `e-for-anything 7000 127.0.0.1:7001 0` and on the other
+/// `e-for-anything 7001 127.0.0.1:7000 1` — `local-handle` must be `0` on exactly one side and
+/// `1` on the other so both peers agree on a single player numbering.
+fn start_p2p_session(mut commands: Commands) {
+    let mut args = std::env::args().skip(1);
+    let local_port = args.next().and_then(|s| s.parse::<u16>().ok());
+    let remote_addr = args
+        .next()
+        .and_then(|s| s.parse::<std::net::SocketAddr>().ok());
+    let local_handle = args.next().and_then(|s| s.parse::<usize>().ok());
+    let (Some(local_port), Some(remote_addr), Some(local_handle)) =
+        (local_port, remote_addr, local_handle)
+    else {
+        commands.insert_resource(
+            bevy_ggrs::Session::SyncTest(
+                SessionBuilder::<NetConfig>::new()
+                    .with_num_players(1)
+                    .with_fps(ROLLBACK_FPS)
+                    .expect("invalid fps")
+                    .add_player(PlayerType::Local, 0)
+                    .expect("failed to add local player")
+                    .start_synctest_session()
+                    .expect("failed to start synctest session"),
+            ),
+        );
+        commands.insert_resource(MeteorRng(RngComponent::with_seed(NET_RNG_SEED)));
+        return;
+    };
+    let remote_handle = 1 - local_handle;
+
+    let socket =
+        UdpNonBlockingSocket::bind_to_port(local_port).expect("failed to bind udp socket");
+
+    let session = SessionBuilder::<NetConfig>::new()
+        .with_num_players(2)
+        .with_fps(ROLLBACK_FPS)
+        .expect("invalid fps")
+        .add_player(PlayerType::Local, local_handle)
+        .expect("failed to add local player")
+        .add_player(PlayerType::Remote(remote_addr), remote_handle)
+        .expect("failed to add remote player")
+        .start_p2p_session(socket)
+        .expect("failed to start p2p session");
+
+    let netplay = NetplaySession { local_handle };
+    info!("Starting co-op session as player {}", netplay.local_handle);
+
+    commands.insert_resource(bevy_ggrs::Session::P2P(session));
+    commands.insert_resource(netplay);
+    commands.insert_resource(MeteorRng(RngComponent::with_seed(NET_RNG_SEED)));
+}
+
+fn init(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    asset_loader: Res<AssetLoader>,
+    mut effects: ResMut<Assets<EffectAsset>>,
+) {
     // Camera
     commands.spawn(Camera2dBundle::default());
 
@@ -200,15 +465,20 @@ fn init(mut commands: Commands, asset_server: Res<AssetServer>) {
         Timer::from_seconds(0.25, TimerMode::Repeating),
     ));
 
+    commands.insert_resource(SpawnTimer(Timer::from_seconds(
+        SPAWN_INTERVAL_START,
+        TimerMode::Repeating,
+    )));
+
     commands.insert_resource(MeteorRes {
         types: vec![
             MeteorType {
-                texture: asset_server.load("spr_meteor_big.png"),
+                texture: asset_loader.images["meteor_big"].clone(),
                 dimensions: BIG_METEOR_VEC,
                 scale: BIG_METEOR_SCALE,
             },
             MeteorType {
-                texture: asset_server.load("spr_meteor_small.png"),
+                texture: asset_loader.images["meteor_small"].clone(),
                 dimensions: SMALL_METEOR_VEC,
                 scale: SMALL_METEOR_SCALE,
             },
@@ -218,51 +488,133 @@ fn init(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.insert_resource(Scoreboard {
         score: 0,
         tick_timer: Timer::from_seconds(0.25, TimerMode::Repeating),
-    })
-}
+    });
 
-// Add the game's entities to our world
-fn setup(
-    mut commands: Commands,
-    mut rng: ResMut<GlobalRng>,
-    asset_server: Res<AssetServer>,
-    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
-    window: Query<&Window>,
-    mut difficulty: ResMut<Difficulty>,
-    mut scoreboard: ResMut<Scoreboard>,
-) {
-    difficulty.0 = INIT_DIFFICULTY;
-    scoreboard.score = 0;
+    commands.insert_resource(Sounds {
+        collision: asset_server.load("sfx_collision.ogg"),
+        death: asset_server.load("sfx_death.ogg"),
+        background: asset_server.load("bgm_ingame.ogg"),
+    });
 
-    let player_normal_anim = asset_server.load("PlayerSheetNormal.png");
-    let atlas_layout = texture_atlas_layouts.add(TextureAtlasLayout::from_grid(
-        Vec2::new(150., 250.),
-        5,
-        1,
-        None,
-        None,
-    ));
+    // Short radial burst of meteor-colored debris, played once per collision.
+    let mut gradient = Gradient::new();
+    gradient.add_key(0.0, Vec4::new(0.8, 0.5, 0.2, 1.0));
+    gradient.add_key(1.0, Vec4::new(0.8, 0.5, 0.2, 0.0));
+
+    let writer = ExprWriter::new();
+    let init_age = SetAttributeModifier::new(Attribute::AGE, writer.lit(0.).expr());
+    let init_lifetime = SetAttributeModifier::new(Attribute::LIFETIME, writer.lit(0.6).expr());
+    let init_pos = SetPositionSphereModifier {
+        center: writer.lit(Vec3::ZERO).expr(),
+        radius: writer.lit(4.).expr(),
+        dimension: ShapeDimension::Volume,
+    };
+    let init_vel = SetVelocitySphereModifier {
+        center: writer.lit(Vec3::ZERO).expr(),
+        speed: writer.lit(120.).expr(),
+    };
+
+    let meteor_burst = effects.add(
+        EffectAsset::new(256, Spawner::once(24.0.into(), true), writer.finish())
+            .with_name("meteor_burst")
+            .init(init_pos)
+            .init(init_vel)
+            .init(init_age)
+            .init(init_lifetime)
+            .render(ColorOverLifetimeModifier { gradient }),
+    );
+
+    commands.insert_resource(ParticleEffects { meteor_burst });
+}
 
+fn spawn_player(
+    commands: &mut Commands,
+    asset_loader: &AssetLoader,
+    handle: usize,
+    translation: Vec3,
+) {
     commands.spawn((
         SpriteSheetBundle {
             transform: Transform {
-                translation: Vec3::new(0.0, bottom(window.single()) + 30., 0.0),
-                scale: Vec3::new(0.2, 0.2, 0.),
+                translation,
+                scale: Vec3::new(PLAYER_SCALE, PLAYER_SCALE, 0.),
                 ..default()
             },
-            texture: player_normal_anim,
+            texture: asset_loader.images["player"].clone(),
             atlas: TextureAtlas {
-                layout: atlas_layout,
+                layout: asset_loader.layouts["player"].clone(),
                 index: 1,
             },
             ..default()
         },
         AnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating), 4, 0),
-        Player,
-        Collider(Vec2::new(150., 250.)),
-        RngComponent::from(&mut rng),
+        Player(handle),
+        RigidBody::KinematicPositionBased,
+        Collider::cuboid(PLAYER_WIDTH / 2., PLAYER_HEIGHT / 2.),
+        ActiveEvents::COLLISION_EVENTS,
         Health(STARTING_HEALTH),
+    ))
+    .add_rollback();
+}
+
+fn spawn_wall(commands: &mut Commands, position: Vec2, size: Vec2) {
+    commands.spawn((
+        Wall,
+        RigidBody::Fixed,
+        Collider::cuboid(size.x / 2., size.y / 2.),
+        Restitution::coefficient(1.0),
+        SpriteBundle {
+            transform: Transform::from_translation(position.extend(0.)),
+            sprite: Sprite {
+                color: WALL_COLOR,
+                custom_size: Some(size),
+                ..default()
+            },
+            ..default()
+        },
     ));
+}
+
+// Add the game's entities to our world
+fn setup(
+    mut commands: Commands,
+    asset_loader: Res<AssetLoader>,
+    window: Query<&Window>,
+    mut difficulty: ResMut<Difficulty>,
+    mut spawn_timer: ResMut<SpawnTimer>,
+    mut scoreboard: ResMut<Scoreboard>,
+    netplay: Option<Res<NetplaySession>>,
+) {
+    difficulty.0 = INIT_DIFFICULTY;
+    spawn_timer
+        .0
+        .set_duration(Duration::from_secs_f32(SPAWN_INTERVAL_START));
+    scoreboard.score = 0;
+
+    let w = window.single();
+    let player_y = bottom(w) + 30.;
+    spawn_player(&mut commands, &asset_loader, 0, Vec3::new(0.0, player_y, 0.0));
+
+    if netplay.is_some() {
+        // Co-op: a second, horizontally offset paddle for player handle 1.
+        spawn_player(&mut commands, &asset_loader, 1, Vec3::new(150.0, player_y, 0.0));
+    }
+
+    spawn_wall(
+        &mut commands,
+        Vec2::new(left(w), 0.),
+        Vec2::new(WALL_THICKNESS, w.height()),
+    );
+    spawn_wall(
+        &mut commands,
+        Vec2::new(right(w), 0.),
+        Vec2::new(WALL_THICKNESS, w.height()),
+    );
+    spawn_wall(
+        &mut commands,
+        Vec2::new(0., top(w)),
+        Vec2::new(w.width(), WALL_THICKNESS),
+    );
 
     // Scoreboard
     commands.spawn((
@@ -290,7 +642,7 @@ fn setup(
         }),
     ));
 
-    let heart_image = asset_server.load("Heart.png");
+    let heart_image = asset_loader.images["heart"].clone();
 
     // To health total
     for i in 0..STARTING_HEALTH {
@@ -437,7 +789,8 @@ fn update_health_bar(
     hearts: Query<(Entity, &HealthBarUi)>,
     mut commands: Commands,
 ) {
-    let cur_health = player_health.single().0;
+    // In co-op, the bar tracks whichever paddle is worse off, since either dying ends the run.
+    let cur_health = player_health.iter().map(|h| h.0).min().unwrap_or(0);
     for (heart_entity, HealthBarUi(idx)) in hearts.iter() {
         if *idx >= cur_health {
             commands.entity(heart_entity).despawn();
@@ -445,107 +798,125 @@ fn update_health_bar(
     }
 }
 
-fn move_player(
-    key_in: Res<ButtonInput<KeyCode>>,
-    mut player_q: Query<&mut Transform, With<Player>>,
-    time: Res<Time>,
+/// Reads this frame's synced `BoxInput`s instead of `ButtonInput<KeyCode>` directly so that
+/// rollback re-simulation of past frames replays identically on both peers.
+fn move_player_rollback(
+    inputs: Res<PlayerInputs<NetConfig>>,
+    mut player_q: Query<(&mut Transform, &Player)>,
     window: Query<&Window>,
 ) {
-    let mut player_transform = player_q.single_mut();
-    let mut direction = 0.0;
+    let w = window.single();
+    for (mut transform, Player(handle)) in &mut player_q {
+        let (input, _) = inputs[*handle];
+        let mut direction = 0.0;
+        if input.inp & INPUT_LEFT != 0 {
+            direction -= 1.0;
+        }
+        if input.inp & INPUT_RIGHT != 0 {
+            direction += 1.0;
+        }
 
-    if key_in.pressed(KeyCode::ArrowLeft) {
-        direction -= 1.0;
+        let player_position = transform.translation.x + direction * PLAYER_SPEED * ROLLBACK_DT;
+        transform.translation.x = player_position.clamp(left(w), right(w));
     }
+}
 
-    if key_in.pressed(KeyCode::ArrowRight) {
-        direction += 1.0;
+fn read_local_inputs(
+    mut commands: Commands,
+    key_in: Res<ButtonInput<KeyCode>>,
+    local_players: Res<LocalPlayers>,
+) {
+    let mut local_inputs = HashMap::new();
+
+    for handle in &local_players.0 {
+        let mut inp = 0u8;
+        if key_in.pressed(KeyCode::ArrowLeft) {
+            inp |= INPUT_LEFT;
+        }
+        if key_in.pressed(KeyCode::ArrowRight) {
+            inp |= INPUT_RIGHT;
+        }
+        local_inputs.insert(*handle, BoxInput { inp });
     }
 
-    // Calculate the new horizontal paddle position based on player input
-    let player_position =
-        player_transform.translation.x + direction * PLAYER_SPEED * time.delta_seconds();
-    let w = window.single();
-    player_transform.translation.x = player_position.clamp(left(w), right(w));
+    commands.insert_resource(LocalInputs::<NetConfig>(local_inputs));
+}
+
+fn update_spawn_timer(difficulty: Res<Difficulty>, mut spawn_timer: ResMut<SpawnTimer>) {
+    let t = (difficulty.0 / MAX_DIFFICULTY).clamp(0.0, 1.0) as f32;
+    let interval = SPAWN_INTERVAL_START + (SPAWN_INTERVAL_FLOOR - SPAWN_INTERVAL_START) * t;
+    spawn_timer.0.set_duration(Duration::from_secs_f32(interval));
 }
 
-fn increase_difficulty(mut difficulty: ResMut<Difficulty>, time: Res<Time>) {
-    difficulty.1.tick(time.delta());
+fn increase_difficulty_rollback(mut difficulty: ResMut<Difficulty>) {
+    difficulty.1.tick(Duration::from_secs_f32(ROLLBACK_DT));
 
     if difficulty.1.finished() {
         difficulty.0 += DIFFICULTY_INCREMENT;
     }
 }
 
-// TODO SpawnMeteorEvent
-fn maybe_spawn_meteor(
-    difficulty: Res<Difficulty>,
+fn update_spawn_timer_rollback(difficulty: Res<Difficulty>, mut spawn_timer: ResMut<SpawnTimer>) {
+    update_spawn_timer(difficulty, spawn_timer.reborrow());
+}
+
+fn maybe_spawn_meteor_rollback(
+    mut spawn_timer: ResMut<SpawnTimer>,
     window: Query<&Window>,
     mut commands: Commands,
     meteor_types: Res<MeteorRes>,
-    mut rng: Query<&mut RngComponent, With<Player>>,
+    mut meteor_rng: ResMut<MeteorRng>,
 ) {
-    let mut c_rng = rng.single_mut();
-    let meteor_type = c_rng.sample(meteor_types.types.as_slice()).expect("A type");
-    let w = window.single();
-    if c_rng.chance(difficulty.0.clamp(0.0, 100.0)) {
-        commands.spawn((
-            Meteor,
-            Collider(meteor_type.dimensions.clone()),
-            Velocity(
-                Vec2 {
-                    x: c_rng.f32_normalized() / 15.,
-                    y: -c_rng.f32(),
-                }
-                .normalize()
-                    * METEOR_SPEED,
-            ),
-            SpriteSheetBundle {
-                transform: Transform::from_translation(Vec3::new(
-                    c_rng.i32((left(w) as i32)..(right(w) as i32)) as f32,
-                    window.single().height() / 2.,
-                    1.0,
-                ))
-                .with_scale(Vec2::splat(meteor_type.scale).extend(1.)),
-                texture: meteor_type.texture.clone(),
-                ..default()
-            },
-            RotationalMomentum(c_rng.f32_normalized()),
-        ));
-    }
-}
-
-fn apply_velocity(mut query: Query<(&mut Transform, &Velocity)>, time: Res<Time>) {
-    for (mut transform, velocity) in &mut query {
-        transform.translation.x += velocity.x * time.delta_seconds();
-        transform.translation.y += velocity.y * time.delta_seconds();
+    spawn_timer.0.tick(Duration::from_secs_f32(ROLLBACK_DT));
+    if !spawn_timer.0.just_finished() {
+        return;
     }
-}
 
-fn apply_rotations(
-    mut query: Query<(&mut Transform, &RotationalMomentum)>,
-    timer: Res<Time>,
-) {
-    for (mut t, RotationalMomentum(ref s)) in query.iter_mut() {
-        t.rotate_z(s * TAU * timer.delta_seconds());
-    }
+    let c_rng = &mut meteor_rng.0;
+    let meteor_type = c_rng.sample(meteor_types.types.as_slice()).expect("A type");
+    let w = window.single();
+    commands.spawn((
+        Meteor,
+        RigidBody::Dynamic,
+        Collider::cuboid(
+            meteor_type.dimensions.x / 2.,
+            meteor_type.dimensions.y / 2.,
+        ),
+        Ccd::enabled(),
+        Restitution::coefficient(1.0),
+        ActiveEvents::COLLISION_EVENTS,
+        Velocity {
+            linvel: Vec2 {
+                x: c_rng.f32_normalized() / 15.,
+                y: -c_rng.f32(),
+            }
+            .normalize()
+                * METEOR_SPEED,
+            angvel: c_rng.f32_normalized() * TAU,
+        },
+        SpriteSheetBundle {
+            transform: Transform::from_translation(Vec3::new(
+                c_rng.i32((left(w) as i32)..(right(w) as i32)) as f32,
+                w.height() / 2.,
+                1.0,
+            ))
+            .with_scale(Vec2::splat(meteor_type.scale).extend(1.)),
+            texture: meteor_type.texture.clone(),
+            ..default()
+        },
+    ))
+    .add_rollback();
 }
 
+// The side and top walls keep meteors in play; only the bottom is a miss.
 fn despawn_offscreen(
     query: Query<(Entity, &Transform), With<Meteor>>,
     mut commands: Commands,
     window: Query<&Window>,
 ) {
-    let max_x = window.single().width() / 2.;
-    let min_x = window.single().width() / -2.;
-    let min_y = window.single().height() / -2.;
-    let max_y = window.single().height() / 2.;
+    let min_y = bottom(window.single());
     for (e, xform) in query.iter() {
-        if xform.translation.y < min_y
-            || xform.translation.y > max_y
-            || xform.translation.x < min_x
-            || xform.translation.x > max_x
-        {
+        if xform.translation.y < min_y {
             commands.entity(e).despawn();
         }
     }
@@ -568,13 +939,18 @@ fn update_scoreboard(
 
 fn apply_damage(
     mut collisions: EventReader<CollisionEvent>,
-    mut player: Query<&mut Health, With<Player>>,
+    mut players: Query<&mut Health, With<Player>>,
     mut death_events: EventWriter<DeathEvent>,
 ) {
-    for _ in collisions.read() {
-        if player.single_mut().0 > 0 {
-            player.single_mut().0 -= 1;
-            if player.single().0 == 0 {
+    for event in collisions.read() {
+        let Ok(mut health) = players.get_mut(event.player) else {
+            continue;
+        };
+
+        if health.0 > 0 {
+            health.0 -= 1;
+            if health.0 == 0 {
+                // Co-op: the run ends as soon as either paddle runs out of health.
                 death_events.send_default();
             }
         } else {
@@ -583,30 +959,100 @@ fn apply_damage(
     }
 }
 
-fn check_for_collisions(
+fn play_collision_sound(
     mut commands: Commands,
-    collider_query: Query<(Entity, &Collider, &Transform), Without<Player>>,
-    player_query: Query<(&Collider, &Transform), With<Player>>,
-    mut collision_events: EventWriter<CollisionEvent>,
+    mut collisions: EventReader<CollisionEvent>,
+    sounds: Res<Sounds>,
+    difficulty: Res<Difficulty>,
 ) {
-    let (player_collider, player_transform) = player_query.single();
-    let player_bb = Aabb2d::new(
-        player_transform.translation.truncate(),
-        (player_transform.scale.truncate() * player_collider.0) / 2.,
-    );
+    for _ in collisions.read() {
+        // Pitch climbs with difficulty so the soundscape intensifies as the game speeds up.
+        let speed = (1.0 + difficulty.0 as f32 * 2.0).clamp(0.5, 3.0);
+        commands.spawn(AudioBundle {
+            source: sounds.collision.clone(),
+            settings: PlaybackSettings::DESPAWN.with_speed(speed),
+        });
+    }
+}
 
-    // check collision with walls
-    for (e, collider, other_transform) in &collider_query {
-        let was_collision = Aabb2d::new(
-            other_transform.translation.truncate(),
-            (other_transform.scale.truncate() * collider.0) / 2.,
-        )
-        .intersects(&player_bb);
+fn spawn_meteor_particles(
+    mut commands: Commands,
+    mut collisions: EventReader<CollisionEvent>,
+    particle_effects: Res<ParticleEffects>,
+) {
+    for event in collisions.read() {
+        commands.spawn((
+            ParticleEffectBundle {
+                effect: ParticleEffect::new(particle_effects.meteor_burst.clone()),
+                transform: Transform::from_translation(event.position),
+                ..default()
+            },
+            ParticleBurst(Timer::from_seconds(0.6, TimerMode::Once)),
+        ));
+    }
+}
 
-        if was_collision {
-            info!("Collision!");
-            collision_events.send_default();
+fn despawn_finished_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut ParticleBurst)>,
+) {
+    for (e, mut burst) in &mut query {
+        burst.0.tick(time.delta());
+        if burst.0.finished() {
             commands.entity(e).despawn();
         }
     }
 }
+
+fn play_death_sound(
+    mut commands: Commands,
+    mut deaths: EventReader<DeathEvent>,
+    sounds: Res<Sounds>,
+) {
+    for _ in deaths.read() {
+        commands.spawn(AudioBundle {
+            source: sounds.death.clone(),
+            settings: PlaybackSettings::DESPAWN,
+        });
+    }
+}
+
+fn start_background_music(mut commands: Commands, sounds: Res<Sounds>) {
+    commands.spawn(AudioBundle {
+        source: sounds.background.clone(),
+        settings: PlaybackSettings::LOOP,
+    });
+}
+
+fn read_collision_events(
+    mut commands: Commands,
+    mut rapier_collisions: EventReader<RapierCollisionEvent>,
+    player_query: Query<Entity, With<Player>>,
+    meteor_query: Query<(Entity, &Transform), With<Meteor>>,
+    mut collision_events: EventWriter<CollisionEvent>,
+) {
+    for event in rapier_collisions.read() {
+        let RapierCollisionEvent::Started(e1, e2, _flags) = event else {
+            continue;
+        };
+
+        let hit = if player_query.contains(*e1) && meteor_query.contains(*e2) {
+            Some((*e1, *e2))
+        } else if player_query.contains(*e2) && meteor_query.contains(*e1) {
+            Some((*e2, *e1))
+        } else {
+            None
+        };
+
+        if let Some((player, meteor)) = hit {
+            let (_, meteor_transform) = meteor_query.get(meteor).expect("meteor just matched");
+            info!("Collision!");
+            collision_events.send(CollisionEvent {
+                position: meteor_transform.translation,
+                player,
+            });
+            commands.entity(meteor).despawn();
+        }
+    }
+}